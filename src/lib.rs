@@ -9,150 +9,440 @@ use wasm_bindgen::prelude::*;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// A small seedable PRNG (SplitMix64) used for reproducible soup seeding.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Draw a float in `[0, 1)` using the top 53 bits of a draw.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// How the grid edges behave when counting neighbours.
 #[wasm_bindgen]
-#[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Cell {
-    Dead = 0,
-    Alive = 1,
+pub enum Boundary {
+    /// Edges wrap around: the grid is a torus.
+    Toroidal = 0,
+    /// Edges are walls: cells off the grid count as dead.
+    Dead = 1,
+}
+
+/// A Life-like ruleset in B/S notation, stored as two bitmasks where bit `n`
+/// means "acts on a cell with exactly `n` live neighbours".
+struct Rule {
+    birth: u16,
+    survival: u16,
+}
+
+impl Rule {
+    /// Conway's Game of Life: `B3/S23`.
+    const CONWAY: Rule = Rule {
+        birth: 1 << 3,
+        survival: (1 << 2) | (1 << 3),
+    };
+
+    /// Parse standard Life-like birth/survival notation, e.g. `"B3/S23"`,
+    /// `"B36/S23"` (HighLife) or `"B2/S"` (Seeds).
+    fn parse(s: &str) -> Result<Rule, String> {
+        let s = s.trim();
+        let (birth, survival) = s
+            .split_once('/')
+            .ok_or_else(|| format!("invalid rule `{s}`: expected `B.../S...`"))?;
+
+        let birth = birth
+            .strip_prefix(['B', 'b'])
+            .ok_or_else(|| format!("invalid rule `{s}`: birth clause must start with `B`"))?;
+        let survival = survival
+            .strip_prefix(['S', 's'])
+            .ok_or_else(|| format!("invalid rule `{s}`: survival clause must start with `S`"))?;
+
+        Ok(Rule {
+            birth: Self::counts_to_mask(birth)?,
+            survival: Self::counts_to_mask(survival)?,
+        })
+    }
+
+    /// Render the ruleset back to B/S notation (the inverse of [`Rule::parse`]).
+    fn to_notation(&self) -> String {
+        let counts = |mask: u16| {
+            (0..=8)
+                .filter(|n| mask & (1 << n) != 0)
+                .filter_map(|n| char::from_digit(n, 10))
+                .collect::<String>()
+        };
+        format!("B{}/S{}", counts(self.birth), counts(self.survival))
+    }
+
+    fn counts_to_mask(counts: &str) -> Result<u16, String> {
+        let mut mask = 0;
+        for ch in counts.chars() {
+            let n = ch
+                .to_digit(10)
+                .ok_or_else(|| format!("invalid neighbour count `{ch}`"))?;
+            if n > 8 {
+                return Err(format!("neighbour count {n} out of range (0-8)"));
+            }
+            mask |= 1 << n;
+        }
+        Ok(mask)
+    }
 }
 
 #[wasm_bindgen]
 pub struct Universe {
     width: u32,
     height: u32,
-    cells: Vec<Cell>,
+    bytes_per_row: u32,
+    cells: Vec<u8>,
+    next: Vec<u8>,
+    ages: Vec<u8>,
+    next_ages: Vec<u8>,
+    rule: Rule,
+    boundary: Boundary,
+    generation: u32,
+    population: u32,
 }
 
 impl Universe {
-    fn get_index(&self, row: u32, column: u32) -> usize {
-        (row * self.width + column) as usize
+    fn get_index(&self, row: u32, column: u32) -> (usize, u8) {
+        let byte = (row * self.bytes_per_row + column / 8) as usize;
+        let mask = 1u8 << (column % 8);
+        (byte, mask)
+    }
+
+    fn is_alive(&self, row: u32, column: u32) -> bool {
+        let (byte, mask) = self.get_index(row, column);
+        self.cells[byte] & mask != 0
+    }
+
+    fn set(&mut self, row: u32, column: u32, alive: bool) {
+        let (byte, mask) = self.get_index(row, column);
+        if alive {
+            self.cells[byte] |= mask;
+            // Live cells always read as age 0 in the ages buffer.
+            self.ages[(row * self.width + column) as usize] = 0;
+        } else {
+            self.cells[byte] &= !mask;
+        }
     }
 
     fn live_neighbor_count(&self, row: u32, column: u32) -> u8 {
         let mut count = 0;
 
-        let north = if row == 0 {
-            self.height - 1
-        } else {
-            row - 1
-        };
+        for delta_row in [-1i32, 0, 1] {
+            for delta_col in [-1i32, 0, 1] {
+                if delta_row == 0 && delta_col == 0 {
+                    continue;
+                }
 
-        let south = if row == self.height - 1 {
-            0
-        } else {
-            row + 1
-        };
+                let neighbor_row = row as i32 + delta_row;
+                let neighbor_col = column as i32 + delta_col;
+
+                let (neighbor_row, neighbor_col) = match self.boundary {
+                    // Wrap off-grid coordinates back around the torus.
+                    Boundary::Toroidal => (
+                        neighbor_row.rem_euclid(self.height as i32) as u32,
+                        neighbor_col.rem_euclid(self.width as i32) as u32,
+                    ),
+                    // Treat anything past a wall as dead.
+                    Boundary::Dead => {
+                        if neighbor_row < 0
+                            || neighbor_row >= self.height as i32
+                            || neighbor_col < 0
+                            || neighbor_col >= self.width as i32
+                        {
+                            continue;
+                        }
+                        (neighbor_row as u32, neighbor_col as u32)
+                    }
+                };
 
-        let west = if column == 0 {
-            self.width - 1
-        } else {
-            column - 1
-        };
+                count += self.is_alive(neighbor_row, neighbor_col) as u8;
+            }
+        }
 
-        let east = if column == self.width - 1 {
-            0
-        } else {
-            column + 1
-        };
+        count
+    }
 
-        let nw = self.get_index(north, west);
-        count += self.cells[nw] as u8;
+    /// Allocate an all-dead universe of the given size with Conway's rules on
+    /// a toroidal board.
+    fn with_size(width: u32, height: u32) -> Universe {
+        let bytes_per_row = width.div_ceil(8);
+        let buf_len = (bytes_per_row * height) as usize;
+        Universe {
+            width,
+            height,
+            bytes_per_row,
+            cells: vec![0; buf_len],
+            next: vec![0; buf_len],
+            ages: vec![0; (width * height) as usize],
+            next_ages: vec![0; (width * height) as usize],
+            rule: Rule::CONWAY,
+            boundary: Boundary::Toroidal,
+            generation: 0,
+            population: 0,
+        }
+    }
 
-        let n = self.get_index(north, column);
-        count += self.cells[n] as u8;
+    /// Recount the live population from the cell buffer. Trailing padding bits
+    /// in each row's last byte are never set, so a plain popcount is exact.
+    fn recount_population(&mut self) {
+        self.population = self.cells.iter().map(|byte| byte.count_ones()).sum();
+    }
 
-        let ne = self.get_index(north, east);
-        count += self.cells[ne] as u8;
+    /// Parse a Golly RLE document into a universe. See [`Universe::from_rle`]
+    /// for the format; this is the fallible core the wasm wrapper unwraps.
+    fn parse_rle(input: &str) -> Result<Universe, String> {
+        let mut width = None;
+        let mut height = None;
+        let mut rule = Rule::CONWAY;
+        let mut body = String::new();
+
+        for line in input.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
 
-        let w = self.get_index(row, west);
-        count += self.cells[w] as u8;
+            // The first non-comment line is the header: `x = w, y = h, rule = ...`.
+            if width.is_none() && line.starts_with('x') {
+                for field in line.split(',') {
+                    let (key, value) = field
+                        .split_once('=')
+                        .ok_or_else(|| format!("invalid header field `{field}`"))?;
+                    match key.trim() {
+                        "x" => {
+                            width = Some(value.trim().parse::<u32>().map_err(|e| e.to_string())?)
+                        }
+                        "y" => {
+                            height = Some(value.trim().parse::<u32>().map_err(|e| e.to_string())?)
+                        }
+                        "rule" => rule = Rule::parse(value.trim())?,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
 
-        let e = self.get_index(row, east);
-        count += self.cells[e] as u8;
+            body.push_str(line);
+        }
 
-        let sw = self.get_index(south, west);
-        count += self.cells[sw] as u8;
+        let width = width.ok_or("missing `x` in RLE header")?;
+        let height = height.ok_or("missing `y` in RLE header")?;
 
-        let s = self.get_index(south, column);
-        count += self.cells[s] as u8;
+        let mut universe = Universe::with_size(width, height);
+        universe.rule = rule;
 
-        let se = self.get_index(south, east);
-        count += self.cells[se] as u8;
+        let mut row = 0;
+        let mut col = 0;
+        let mut count = 0;
+        for ch in body.chars() {
+            match ch {
+                c if c.is_ascii_digit() => count = count * 10 + c.to_digit(10).unwrap(),
+                'b' | 'o' => {
+                    let run = count.max(1);
+                    for _ in 0..run {
+                        if ch == 'o' && row < height && col < width {
+                            universe.set(row, col, true);
+                        }
+                        col += 1;
+                    }
+                    count = 0;
+                }
+                '$' => {
+                    row += count.max(1);
+                    col = 0;
+                    count = 0;
+                }
+                '!' => break,
+                c if c.is_whitespace() => {}
+                other => return Err(format!("invalid RLE tag `{other}`")),
+            }
+        }
 
-        count
+        universe.recount_population();
+        Ok(universe)
+    }
+
+    fn encode_rle_run(line: &mut String, run: u32, alive: bool) {
+        if run > 1 {
+            line.push_str(&run.to_string());
+        }
+        line.push(if alive { 'o' } else { 'b' });
     }
 }
 
 #[wasm_bindgen]
 impl Universe {
     pub fn tick(&mut self) {
-        let mut next = self.cells.clone();
+        for byte in self.next.iter_mut() {
+            *byte = 0;
+        }
+
+        let mut population = 0;
 
         for row in 0..self.height {
             for col in 0..self.width {
-                let idx = self.get_index(row, col);
-                let cell = self.cells[idx];
+                let cell = self.is_alive(row, col);
                 let live_neighbors = self.live_neighbor_count(row, col);
 
-                let next_cell = match (cell, live_neighbors) {
-                    // Rule 1: Any live cell with fewer than two live neighbours
-                    // dies, as if caused by underpopulation.
-                    (Cell::Alive, x) if x < 2 => Cell::Dead,
-                    // Rule 2: Any live cell with two or three live neighbours
-                    // lives on to the next generation.
-                    (Cell::Alive, 2) | (Cell::Alive, 3) => Cell::Alive,
-                    // Rule 3: Any live cell with more than three live
-                    // neighbours dies, as if by overpopulation.
-                    (Cell::Alive, x) if x > 3 => Cell::Dead,
-                    // Rule 4: Any dead cell with exactly three live neighbours
-                    // becomes a live cell, as if by reproduction.
-                    (Cell::Dead, 3) => Cell::Alive,
-                    // All other cells remain in the same state.
-                    (otherwise, _) => otherwise,
+                // A live cell survives if its neighbour count is in the
+                // survival mask; a dead cell is born if it is in the birth
+                // mask. Every other cell is dead in the next generation.
+                let next_cell = if cell {
+                    self.rule.survival & (1 << live_neighbors) != 0
+                } else {
+                    self.rule.birth & (1 << live_neighbors) != 0
                 };
 
-                next[idx] = next_cell;
+                let age_idx = (row * self.width + col) as usize;
+                if next_cell {
+                    let (byte, mask) = self.get_index(row, col);
+                    self.next[byte] |= mask;
+                    self.next_ages[age_idx] = 0;
+                    population += 1;
+                } else if cell {
+                    // Died this generation: start the decay counter.
+                    self.next_ages[age_idx] = 1;
+                } else {
+                    // Still dead: age towards the background colour.
+                    self.next_ages[age_idx] = self.ages[age_idx].saturating_add(1);
+                }
             }
         }
 
-        self.cells = next;
+        std::mem::swap(&mut self.cells, &mut self.next);
+        std::mem::swap(&mut self.ages, &mut self.next_ages);
+        self.generation += 1;
+        self.population = population;
     }
 
     pub fn new() -> Universe {
-        //let width = 64;
-        //let height = 64;
         let width = 64;
         let height = 64;
 
-        let cells = (0..width * height)
-            .map(|i| {
-                let column = i %width;
-                let row = i / width;
-
-                if row > 4 && row <= 16 && column > width - row - 3*width/4+1 && column < width/4 {
-                    Cell::Alive
-                } else if row > 4 && row <= 16 && column > width/4-1 && column < width/4 + row - 1 {
-                    Cell::Alive
-                } else if row > 4 && row <= 16 && column > width/2-1 && column > width - row - width/4+1 && column < 3*width/4 {
-                    Cell::Alive
-                } else if row > 4 && row <= 16 && column > 3*width/4-1 && column < 3*width/4 + row - 1 {
-                    Cell::Alive
-                } else if row > 16 && row <= 24 && ((column > 1 && column < width/4) || ( column > 3*width/4 && column < width - 1)) {
-                    Cell::Alive
-                } else if row > 24 && column > row-24 && column < (width - row + 24) {
-                    Cell::Alive
+        let mut universe = Universe::with_size(width, height);
+
+        for row in 0..height {
+            for column in 0..width {
+                let alive = if row > 4 && row <= 16 && column > width - row - 3 * width / 4 + 1 && column < width / 4 {
+                    true
+                } else if row > 4 && row <= 16 && column > width / 4 - 1 && column < width / 4 + row - 1 {
+                    true
+                } else if row > 4 && row <= 16 && column > width / 2 - 1 && column > width - row - width / 4 + 1 && column < 3 * width / 4 {
+                    true
+                } else if row > 4 && row <= 16 && column > 3 * width / 4 - 1 && column < 3 * width / 4 + row - 1 {
+                    true
+                } else if row > 16 && row <= 24 && ((column > 1 && column < width / 4) || (column > 3 * width / 4 && column < width - 1)) {
+                    true
                 } else {
-                    Cell::Dead
+                    row > 24 && column > row - 24 && column < (width - row + 24)
+                };
+
+                universe.set(row, column, alive);
+            }
+        }
+
+        universe.recount_population();
+        universe
+    }
+
+    /// Load a universe from a Golly [Run Length Encoded][rle] pattern. The
+    /// header sizes the grid and an optional `rule = ...` field sets the
+    /// ruleset; `#`-prefixed comment lines are ignored. Panics if the document
+    /// is malformed.
+    ///
+    /// [rle]: https://conwaylife.com/wiki/Run_Length_Encoded
+    pub fn from_rle(rle: &str) -> Universe {
+        Universe::parse_rle(rle).unwrap_or_else(|e| panic!("invalid RLE: {e}"))
+    }
+
+    /// Serialise the universe to Golly RLE, including a `rule` field. Trailing
+    /// dead cells on each row are dropped and the stream is terminated with `!`.
+    pub fn to_rle(&self) -> String {
+        let mut out = format!(
+            "x = {}, y = {}, rule = {}\n",
+            self.width,
+            self.height,
+            self.rule.to_notation()
+        );
+
+        let mut rows = Vec::with_capacity(self.height as usize);
+        for row in 0..self.height {
+            let mut line = String::new();
+            let mut run_len = 0;
+            let mut run_alive = false;
+            for col in 0..self.width {
+                let alive = self.is_alive(row, col);
+                if run_len == 0 {
+                    run_alive = alive;
+                    run_len = 1;
+                } else if alive == run_alive {
+                    run_len += 1;
+                } else {
+                    Self::encode_rle_run(&mut line, run_len, run_alive);
+                    run_alive = alive;
+                    run_len = 1;
                 }
-            })
-            .collect();
+            }
+            // Collapse trailing dead cells by only emitting a final live run.
+            if run_len > 0 && run_alive {
+                Self::encode_rle_run(&mut line, run_len, true);
+            }
+            rows.push(line);
+        }
 
-        Universe {
-            width,
-            height,
-            cells,
+        out.push_str(&rows.join("$"));
+        out.push('!');
+        out
+    }
+
+    /// Set the ruleset from Life-like B/S notation (e.g. `"B36/S23"`).
+    /// Returns an error if the notation is malformed or a count exceeds 8.
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), JsValue> {
+        self.rule = Rule::parse(rule).map_err(|e| JsValue::from_str(&e))?;
+        Ok(())
+    }
+
+    /// Choose whether the grid edges wrap (`Toroidal`) or act as walls
+    /// (`Dead`).
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        self.boundary = boundary;
+    }
+
+    /// Build a universe of the given size where each cell is live with
+    /// probability `density`, seeded by `seed` for reproducible results.
+    pub fn new_random(width: u32, height: u32, density: f64, seed: u64) -> Universe {
+        let mut universe = Universe::with_size(width, height);
+        universe.randomize(density, seed);
+        universe
+    }
+
+    /// Reseed an existing universe in place, redrawing every cell.
+    pub fn randomize(&mut self, density: f64, seed: u64) {
+        let mut rng = SplitMix64::new(seed);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                self.set(row, col, rng.next_f64() < density);
+            }
         }
+        self.recount_population();
     }
 
     pub fn width(&self) -> u32 {
@@ -163,13 +453,44 @@ impl Universe {
         self.height
     }
 
-    pub fn cells(&self) -> *const Cell {
+    /// Number of bytes used to store one row of the bit-packed grid. JS reads
+    /// the cell buffer as a `Uint8Array` and finds a cell's bit at byte
+    /// `row * bytes_per_row + column / 8`, mask `1 << (column % 8)`.
+    pub fn bytes_per_row(&self) -> u32 {
+        self.bytes_per_row
+    }
+
+    pub fn cells(&self) -> *const u8 {
         self.cells.as_ptr()
     }
 
+    /// Pointer to a `width * height` byte buffer of per-cell ages: `0` for
+    /// live cells, otherwise the number of generations since the cell died
+    /// (saturating at 255). JS maps this to a decay colour ramp.
+    pub fn ages(&self) -> *const u8 {
+        self.ages.as_ptr()
+    }
+
+    /// Number of generations advanced since creation or the last
+    /// [`Universe::reset_generation`].
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Number of live cells. Zero means the universe has gone extinct.
+    pub fn population(&self) -> u32 {
+        self.population
+    }
+
+    /// Reset the generation counter to zero without touching the cells.
+    pub fn reset_generation(&mut self) {
+        self.generation = 0;
+    }
+
     pub fn toggle_cell(&mut self, row: u32, column: u32) {
-        let idx = self.get_index(row, column);
-        self.cells[idx].toggle();
+        let alive = self.is_alive(row, column);
+        self.set(row, column, !alive);
+        self.recount_population();
     }
 
     pub fn add_glider(&mut self, row: u32, column: u32) {
@@ -177,45 +498,36 @@ impl Universe {
             for delta_col in [self.width - 1, 0, 1].iter().cloned() {
                 let neighbor_row = (row + delta_row) % self.height;
                 let neighbor_col = (column + delta_col) % self.width;
-                let idx = self.get_index(neighbor_row, neighbor_col);
                 if delta_row == 1 && delta_col == self.width - 1 {
-                    self.cells[idx] = Cell::Dead;
-                    continue
+                    self.set(neighbor_row, neighbor_col, false);
+                    continue;
                 }
                 if delta_row == 1 && delta_col == 0 {
-                    self.cells[idx] = Cell::Alive;
-                    continue
+                    self.set(neighbor_row, neighbor_col, true);
+                    continue;
                 }
                 if delta_row == 1 && delta_col == 1 {
-                    self.cells[idx] = Cell::Dead;
-                    continue
+                    self.set(neighbor_row, neighbor_col, false);
+                    continue;
                 }
                 if delta_row == 0 && delta_col == self.width - 1 {
-                    self.cells[idx] = Cell::Dead;
-                    continue
+                    self.set(neighbor_row, neighbor_col, false);
+                    continue;
                 }
                 if delta_row == 0 && delta_col == 0 {
-                    self.cells[idx] = Cell::Dead;
-                    continue
+                    self.set(neighbor_row, neighbor_col, false);
+                    continue;
                 }
                 if delta_row == 0 && delta_col == 1 {
-                    self.cells[idx] = Cell::Alive;
-                    continue
+                    self.set(neighbor_row, neighbor_col, true);
+                    continue;
                 }
                 if delta_row == self.height - 1 {
-                    self.cells[idx] = Cell::Alive;
-                    continue
+                    self.set(neighbor_row, neighbor_col, true);
+                    continue;
                 }
             }
         }
-    }
-}
-
-impl Cell {
-    fn toggle(&mut self) {
-        *self = match *self {
-            Cell::Dead => Cell::Alive,
-            Cell::Alive => Cell::Dead,
-        };
+        self.recount_population();
     }
 }